@@ -0,0 +1,137 @@
+//! The inverse of [`crate::payload::Payload::extract`]: repacks a set of
+//! `<name>.img` files into a fresh `payload.bin`.
+
+use std::fs::File;
+use std::io::{self, BufReader, Result, Write};
+use std::path::{Path, PathBuf};
+
+use prost::Message;
+
+use crate::partition::PartitionEncoder;
+use crate::payload::update_metadata::{
+    install_operation::Type, DeltaArchiveManifest, Extent, InstallOperation, PartitionInfo,
+    PartitionUpdate, Signatures,
+};
+use crate::payload::{PAYLOAD_HEADER_MAGIC, PAYLOAD_MAJOR_VERSION};
+
+/// Compression to use for the operations a [`PayloadBuilder`] emits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Xz,
+    Zstd,
+}
+
+impl Compression {
+    fn operation_type(self) -> Type {
+        match self {
+            Compression::None => Type::Replace,
+            Compression::Xz => Type::ReplaceXz,
+            Compression::Zstd => Type::ReplaceZstd,
+        }
+    }
+}
+
+/// Builds a major-version-2 `payload.bin` out of a set of partition images.
+pub struct PayloadBuilder {
+    compression: Compression,
+    zstd_level: i32,
+    partitions: Vec<(String, PathBuf)>,
+}
+
+impl PayloadBuilder {
+    pub fn new(compression: Compression) -> Self {
+        Self {
+            compression,
+            zstd_level: 19,
+            partitions: Vec::new(),
+        }
+    }
+
+    pub fn with_zstd_level(mut self, level: i32) -> Self {
+        self.zstd_level = level;
+        self
+    }
+
+    pub fn add_partition(&mut self, name: impl Into<String>, image_path: impl Into<PathBuf>) -> &mut Self {
+        self.partitions.push((name.into(), image_path.into()));
+        self
+    }
+
+    /// Encodes every added partition and writes the resulting payload to
+    /// `output_path`.
+    ///
+    /// Each partition's operation data blobs are held in memory while that
+    /// partition is encoded, then staged in a scratch file next to
+    /// `output_path`, since `data_offset` values in the manifest aren't known
+    /// until every partition has been encoded.
+    pub fn write(&self, output_path: &Path) -> Result<()> {
+        let encoder =
+            PartitionEncoder::new(self.compression.operation_type()).with_zstd_level(self.zstd_level);
+
+        let blobs_path = output_path.with_extension("blobs.tmp");
+        let mut manifest = DeltaArchiveManifest::default();
+        let mut blob_offset = 0u64;
+
+        {
+            let mut blobs_file = File::create(&blobs_path)?;
+
+            for (name, image_path) in &self.partitions {
+                let image = File::open(image_path)?;
+                let size = image.metadata()?.len();
+                let (encoded_ops, image_hash) = encoder.encode(BufReader::new(image))?;
+
+                let operations = encoded_ops
+                    .into_iter()
+                    .map(|op| {
+                        let data_offset = blob_offset;
+                        let data_length = op.data.len() as u64;
+                        blobs_file.write_all(&op.data)?;
+                        blob_offset += data_length;
+
+                        Ok(InstallOperation {
+                            r#type: Some(op.operation_type as i32),
+                            data_offset: Some(data_offset),
+                            data_length: Some(data_length),
+                            data_sha256_hash: Some(op.data_sha256_hash.to_vec()),
+                            dst_extents: vec![Extent {
+                                start_block: Some(op.start_block),
+                                num_blocks: Some(op.num_blocks),
+                            }],
+                            ..Default::default()
+                        })
+                    })
+                    .collect::<Result<_>>()?;
+
+                manifest.partitions.push(PartitionUpdate {
+                    partition_name: name.clone(),
+                    operations,
+                    new_partition_info: Some(PartitionInfo {
+                        size: Some(size),
+                        hash: Some(image_hash.to_vec()),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                });
+            }
+        }
+
+        let manifest_bytes = manifest.encode_to_vec();
+        let signature_bytes = Signatures::default().encode_to_vec();
+
+        let mut file = File::create(output_path)?;
+        file.write_all(PAYLOAD_HEADER_MAGIC.as_bytes())?;
+        file.write_all(&PAYLOAD_MAJOR_VERSION.to_be_bytes())?;
+        file.write_all(&(manifest_bytes.len() as u64).to_be_bytes())?;
+        file.write_all(&(signature_bytes.len() as u32).to_be_bytes())?;
+        file.write_all(&manifest_bytes)?;
+        file.write_all(&signature_bytes)?;
+
+        let mut blobs_file = File::open(&blobs_path)?;
+        io::copy(&mut blobs_file, &mut file)?;
+        drop(blobs_file);
+        std::fs::remove_file(&blobs_path)?;
+
+        Ok(())
+    }
+}
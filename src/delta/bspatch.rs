@@ -0,0 +1,128 @@
+use std::io::{self, Read};
+
+use bzip2::bufread::BzDecoder;
+
+const MAGIC: &[u8; 8] = b"BSDIFF40";
+const HEADER_LEN: usize = 32;
+
+/// Applies a bsdiff patch — the classic Colin Percival `BSDIFF40` format
+/// used by real `SOURCE_BSDIFF`/`BROTLI_BSDIFF` operations — against `old`,
+/// producing exactly `new_len` bytes.
+///
+/// Layout: an 8-byte `"BSDIFF40"` magic, three sign-magnitude little-endian
+/// 64-bit lengths (bzip2'd control block length, bzip2'd diff block length,
+/// new file length), then the bzip2'd control/diff/extra blocks back to
+/// back. The control block holds `(add_len, copy_len, seek)` triples (also
+/// sign-magnitude 64-bit values); each triple contributes `add_len` bytes of
+/// `old[oldpos..] + diff` followed by `copy_len` bytes copied verbatim from
+/// the extra block, before `oldpos` advances by `add_len + seek`.
+pub(crate) fn apply(old: &[u8], patch: &[u8], new_len: usize) -> io::Result<Vec<u8>> {
+    if patch.len() < HEADER_LEN || &patch[..8] != MAGIC {
+        return Err(invalid("not a BSDIFF40 patch"));
+    }
+
+    let ctrl_len = read_off(&patch[8..16])?;
+    let diff_len = read_off(&patch[16..24])?;
+    let expected_new_len = read_off(&patch[24..32])?;
+    if expected_new_len != new_len as i64 {
+        return Err(invalid(&format!(
+            "bspatch header declares {expected_new_len} bytes, expected {new_len}"
+        )));
+    }
+
+    let ctrl_start = HEADER_LEN;
+    let diff_start = checked_add(ctrl_start, ctrl_len)?;
+    let extra_start = checked_add(diff_start, diff_len)?;
+
+    let ctrl_block = patch.get(ctrl_start..diff_start).ok_or_else(truncated)?;
+    let diff_block = patch.get(diff_start..extra_start).ok_or_else(truncated)?;
+    let extra_block = patch.get(extra_start..).ok_or_else(truncated)?;
+
+    let ctrl = decompress(ctrl_block)?;
+    let diff = decompress(diff_block)?;
+    let extra = decompress(extra_block)?;
+
+    let mut out = Vec::with_capacity(new_len);
+    let mut old_pos = 0i64;
+    let mut ctrl_pos = 0usize;
+    let mut diff_pos = 0usize;
+    let mut extra_pos = 0usize;
+
+    while out.len() < new_len {
+        let add_len = read_off(take(&ctrl, &mut ctrl_pos, 8)?)?;
+        let copy_len = read_off(take(&ctrl, &mut ctrl_pos, 8)?)?;
+        let seek = read_off(take(&ctrl, &mut ctrl_pos, 8)?)?;
+
+        let add_len = usize::try_from(add_len).map_err(|_| invalid("negative add_len in bspatch control block"))?;
+        let diff_chunk = diff.get(diff_pos..diff_pos + add_len).ok_or_else(truncated)?;
+        for (i, &d) in diff_chunk.iter().enumerate() {
+            let old_byte = usize::try_from(old_pos + i as i64)
+                .ok()
+                .and_then(|idx| old.get(idx))
+                .copied()
+                .unwrap_or(0);
+            out.push(old_byte.wrapping_add(d));
+        }
+        diff_pos += add_len;
+
+        let copy_len =
+            usize::try_from(copy_len).map_err(|_| invalid("negative copy_len in bspatch control block"))?;
+        let extra_chunk = extra.get(extra_pos..extra_pos + copy_len).ok_or_else(truncated)?;
+        out.extend_from_slice(extra_chunk);
+        extra_pos += copy_len;
+
+        old_pos += add_len as i64 + seek;
+    }
+
+    if out.len() != new_len {
+        return Err(invalid(&format!(
+            "bspatch produced {} bytes, expected {new_len}",
+            out.len()
+        )));
+    }
+
+    Ok(out)
+}
+
+/// Decodes bsdiff's sign-magnitude little-endian 64-bit integer encoding
+/// (`offtin`): the low 63 bits are the magnitude, the top bit is a sign
+/// flag rather than the value being two's-complement.
+fn read_off(bytes: &[u8]) -> io::Result<i64> {
+    let bytes: [u8; 8] = bytes.try_into().map_err(|_| truncated())?;
+    let raw = u64::from_le_bytes(bytes);
+    let magnitude = (raw & 0x7fff_ffff_ffff_ffff) as i64;
+    Ok(if raw & 0x8000_0000_0000_0000 != 0 {
+        -magnitude
+    } else {
+        magnitude
+    })
+}
+
+fn decompress(block: &[u8]) -> io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    BzDecoder::new(block).read_to_end(&mut out)?;
+    Ok(out)
+}
+
+fn take<'a>(buf: &'a [u8], pos: &mut usize, len: usize) -> io::Result<&'a [u8]> {
+    let slice = buf.get(*pos..*pos + len).ok_or_else(truncated)?;
+    *pos += len;
+    Ok(slice)
+}
+
+/// Adds two lengths taken from the (untrusted) patch header, erroring
+/// instead of overflowing/panicking on a malformed header.
+fn checked_add(a: usize, b: i64) -> io::Result<usize> {
+    usize::try_from(b)
+        .ok()
+        .and_then(|b| a.checked_add(b))
+        .ok_or_else(truncated)
+}
+
+fn truncated() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "truncated bspatch stream")
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
@@ -0,0 +1,13 @@
+//! Delta (incremental) OTA operation support: operations that reconstruct a
+//! destination extent from a source partition image instead of from data
+//! embedded in the payload.
+//!
+//! `SOURCE_COPY` and the bsdiff-family operations (`SOURCE_BSDIFF`,
+//! `BROTLI_BSDIFF`) are implemented. `PUFFDIFF` is a known, tracked gap: see
+//! [`puffpatch`] for why.
+
+mod bspatch;
+mod puffpatch;
+
+pub(crate) use bspatch::apply as apply_bspatch;
+pub(crate) use puffpatch::apply as apply_puffpatch;
@@ -0,0 +1,15 @@
+use std::io;
+
+/// Applies a puffin patch against `source`.
+///
+/// Puffin patches describe edits against the raw, re-framable deflate
+/// stream nested inside a partition image rather than against plain bytes,
+/// which needs a full deflate reframer to reconstruct. That reframer isn't
+/// implemented yet, so `PUFFDIFF` operations are reported as unsupported
+/// instead of silently producing a corrupt image.
+pub(crate) fn apply(_source: &[u8], _patch: &[u8], _new_len: usize) -> io::Result<Vec<u8>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "PUFFDIFF operations are not yet supported",
+    ))
+}
@@ -0,0 +1,11 @@
+mod builder;
+mod delta;
+pub mod partition;
+pub mod payload;
+pub mod source;
+
+#[cfg(feature = "fuse")]
+pub mod mount;
+
+pub use builder::{Compression, PayloadBuilder};
+pub use payload::Payload;
@@ -1,8 +1,26 @@
 use clap::Parser;
-use payload_dumper_rs::Payload;
+use payload_dumper_rs::source::is_url;
+use payload_dumper_rs::{Compression, Payload, PayloadBuilder};
 use rayon::prelude::*;
 use std::path::{Path, PathBuf};
 
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum CompressionArg {
+    None,
+    Xz,
+    Zstd,
+}
+
+impl From<CompressionArg> for Compression {
+    fn from(value: CompressionArg) -> Self {
+        match value {
+            CompressionArg::None => Compression::None,
+            CompressionArg::Xz => Compression::Xz,
+            CompressionArg::Zstd => Compression::Zstd,
+        }
+    }
+}
+
 #[derive(Parser, Debug)]
 #[command(
     author = "Inam Ul Haq",
@@ -22,13 +40,33 @@ struct Arguments {
     #[arg(short = 'c', long = "num_threads", default_value = "4")]
     num_threads: usize,
 
-    payload_path: PathBuf,
-}
+    /// Directory of old `<name>.img` files to patch against for delta
+    /// (incremental) partitions.
+    #[arg(long = "source-dir", value_name = "DIR")]
+    source_dir: Option<PathBuf>,
 
-impl Arguments {
-    fn payload_path(&self) -> &Path {
-        self.payload_path.as_path()
-    }
+    /// Mount the payload's partitions as `<name>.img` files at this directory
+    /// instead of extracting them, decoding each on demand as it is read.
+    #[cfg(feature = "fuse")]
+    #[arg(long = "mount", value_name = "DIR")]
+    mount: Option<PathBuf>,
+
+    /// Build a payload.bin out of the `<name>.img` files (named via
+    /// `--partitions`) found in this directory, instead of extracting one.
+    #[arg(long = "create", value_name = "IMAGES_DIR")]
+    create: Option<PathBuf>,
+
+    /// Compression to use for operations written by `--create`.
+    #[arg(long = "compression", value_enum, default_value = "zstd")]
+    compression: CompressionArg,
+
+    /// Zstd compression level to use for operations written by `--create`.
+    #[arg(long = "zstd-level", default_value = "19")]
+    zstd_level: i32,
+
+    /// Local path or `http(s)://` URL of the payload to extract. Not used
+    /// with `--create`.
+    payload_path: Option<String>,
 }
 
 fn generate_output_path(base_dir: &Path) -> PathBuf {
@@ -39,26 +77,69 @@ fn generate_output_path(base_dir: &Path) -> PathBuf {
     base_dir.join(dir_name)
 }
 
+fn create_payload(args: &Arguments, images_dir: &Path) -> Result<(), std::io::Error> {
+    if args.partitions.is_empty() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "--create requires --partitions to list which images to pack",
+        ));
+    }
+
+    let output_path = args
+        .output
+        .clone()
+        .unwrap_or_else(|| PathBuf::from("payload.bin"));
+
+    let mut builder =
+        PayloadBuilder::new(args.compression.into()).with_zstd_level(args.zstd_level);
+    for name in &args.partitions {
+        builder.add_partition(name.clone(), images_dir.join(format!("{name}.img")));
+    }
+    builder.write(&output_path)?;
+
+    println!("Wrote payload to {}", output_path.display());
+    Ok(())
+}
+
 fn main() -> Result<(), std::io::Error> {
     let args: Arguments = Arguments::parse();
 
-    let payload_path = args.payload_path();
+    if let Some(images_dir) = args.create.clone() {
+        return create_payload(&args, &images_dir);
+    }
+
+    let payload_path = args.payload_path.as_deref().ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "a payload path or URL is required unless --create is given",
+        )
+    })?;
+
     /* Default Path to use if output path is not provided */
-    let default_path = generate_output_path(payload_path.parent().unwrap());
-    let payload = Payload::try_from(args.payload_path())?;
+    let default_path = if is_url(payload_path) {
+        generate_output_path(Path::new("."))
+    } else {
+        generate_output_path(Path::new(payload_path).parent().unwrap())
+    };
+    let payload = Payload::try_from(payload_path)?;
     println!("Payload: {}", payload.header());
     if args.list {
         payload.print_partitions();
         return Ok(());
     }
 
-    let output_dir = args.output.unwrap_or_else(|| default_path);
+    #[cfg(feature = "fuse")]
+    if let Some(mountpoint) = args.mount {
+        return payload_dumper_rs::mount::mount(payload, &mountpoint);
+    }
+
+    let output_dir = args.output.clone().unwrap_or_else(|| default_path);
     std::fs::create_dir_all(&output_dir)?;
 
     let partitions = if args.partitions.is_empty() {
         payload.partition_list()
     } else {
-        args.partitions
+        args.partitions.clone()
     };
 
     rayon::ThreadPoolBuilder::new()
@@ -66,8 +147,33 @@ fn main() -> Result<(), std::io::Error> {
         .build_global()
         .unwrap();
 
-    partitions
+    let results: Vec<(&String, std::io::Result<()>)> = partitions
         .par_iter()
-        .try_for_each(|partition| payload.extract(partition, output_dir.as_path()))?;
+        .map(|partition| {
+            (
+                partition,
+                payload.extract(partition, output_dir.as_path(), args.source_dir.as_deref()),
+            )
+        })
+        .collect();
+
+    let mut failed = 0;
+    for (partition, result) in &results {
+        if let Err(err) = result {
+            failed += 1;
+            eprintln!("{partition}: FAILED ({err})");
+        }
+    }
+    println!(
+        "\nVerification summary: {} OK, {failed} FAILED",
+        results.len() - failed
+    );
+
+    if failed > 0 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("{failed} partition(s) failed verification"),
+        ));
+    }
     Ok(())
 }
@@ -0,0 +1,53 @@
+use crate::payload::update_metadata::{install_operation::Type, InstallOperation};
+
+/// One operation's contribution to a partition image, keyed by the block
+/// range it fills in. Built once per partition and binary-searched on every
+/// FUSE read.
+#[derive(Debug, Clone)]
+pub(super) struct IndexEntry {
+    pub start_block: u64,
+    pub end_block: u64,
+    pub data_offset: u64,
+    pub data_length: u64,
+    pub operation_type: Type,
+}
+
+/// Sorted, non-overlapping index of a partition's operations by destination
+/// block range, used to resolve an arbitrary byte range read into the
+/// operations that back it.
+pub(super) struct PartitionIndex {
+    entries: Vec<IndexEntry>,
+}
+
+impl PartitionIndex {
+    pub fn build(operations: &[InstallOperation]) -> Self {
+        let mut entries: Vec<IndexEntry> = operations
+            .iter()
+            .filter_map(|operation| {
+                let dst_extent = operation.dst_extents.first()?;
+                Some(IndexEntry {
+                    start_block: dst_extent.start_block(),
+                    end_block: dst_extent.start_block() + dst_extent.num_blocks(),
+                    data_offset: operation.data_offset(),
+                    data_length: operation.data_length(),
+                    operation_type: operation.r#type(),
+                })
+            })
+            .collect();
+
+        entries.sort_by_key(|entry| entry.start_block);
+        Self { entries }
+    }
+
+    /// Returns every entry whose block range overlaps `[start_block, end_block)`,
+    /// in ascending order.
+    pub fn overlapping(&self, start_block: u64, end_block: u64) -> &[IndexEntry] {
+        let first = self
+            .entries
+            .partition_point(|entry| entry.end_block <= start_block);
+        let last = self
+            .entries
+            .partition_point(|entry| entry.start_block < end_block);
+        &self.entries[first..last.max(first)]
+    }
+}
@@ -0,0 +1,291 @@
+//! On-demand FUSE mount of a payload's partitions.
+//!
+//! Each partition is exposed as `<name>.img` sized to its
+//! `new_partition_info.size`. Reads are served lazily straight out of the
+//! payload's data blobs instead of extracting everything up front, the same
+//! way pxar's fuse layer serves archive members without unpacking them.
+
+mod index;
+
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, UNIX_EPOCH};
+
+use fuser::{
+    FileAttr, FileType, Filesystem, MountOption, ReplyAttr, ReplyData, ReplyDirectory, ReplyEntry,
+    Request,
+};
+
+use crate::partition::decode_blob;
+use crate::partition::PartitionExtent;
+use crate::payload::Payload;
+use index::PartitionIndex;
+
+const TTL: Duration = Duration::from_secs(60 * 60 * 24 * 365);
+
+/// Most-recently-decoded operation, so sequential reads within one
+/// compressed operation don't re-decompress on every FUSE request.
+struct DecodedBlobCache {
+    key: (u64, u64),
+    blob: Vec<u8>,
+}
+
+struct PartitionFile {
+    name: String,
+    size: u64,
+    index: PartitionIndex,
+}
+
+pub struct PayloadFs {
+    payload: Payload,
+    partitions: Vec<PartitionFile>,
+    cache: Mutex<Option<DecodedBlobCache>>,
+}
+
+impl PayloadFs {
+    pub fn new(payload: Payload) -> Self {
+        let partitions = payload
+            .partitions()
+            .iter()
+            .map(|partition| PartitionFile {
+                name: partition.partition_name.clone(),
+                size: partition.new_partition_info.as_ref().unwrap().size(),
+                index: PartitionIndex::build(&partition.operations),
+            })
+            .collect();
+
+        Self {
+            payload,
+            partitions,
+            cache: Mutex::new(None),
+        }
+    }
+
+    /// Inode `1` is the mount root; partition files start at `2`, in the same
+    /// order as [`Payload::partition_list`].
+    fn partition_by_ino(&self, ino: u64) -> Option<&PartitionFile> {
+        ino.checked_sub(2)
+            .and_then(|idx| self.partitions.get(idx as usize))
+    }
+
+    fn ino_by_name(&self, name: &str) -> Option<u64> {
+        self.partitions
+            .iter()
+            .position(|p| p.name == name)
+            .map(|idx| idx as u64 + 2)
+    }
+
+    fn file_attr(ino: u64, size: u64) -> FileAttr {
+        FileAttr {
+            ino,
+            size,
+            blocks: size.div_ceil(PartitionExtent::BLOCK_SIZE),
+            atime: UNIX_EPOCH,
+            mtime: UNIX_EPOCH,
+            ctime: UNIX_EPOCH,
+            crtime: UNIX_EPOCH,
+            kind: FileType::RegularFile,
+            perm: 0o444,
+            nlink: 1,
+            uid: 0,
+            gid: 0,
+            rdev: 0,
+            blksize: PartitionExtent::BLOCK_SIZE as u32,
+            flags: 0,
+        }
+    }
+
+    const ROOT_ATTR: FileAttr = FileAttr {
+        ino: 1,
+        size: 0,
+        blocks: 0,
+        atime: UNIX_EPOCH,
+        mtime: UNIX_EPOCH,
+        ctime: UNIX_EPOCH,
+        crtime: UNIX_EPOCH,
+        kind: FileType::Directory,
+        perm: 0o555,
+        nlink: 2,
+        uid: 0,
+        gid: 0,
+        rdev: 0,
+        blksize: 512,
+        flags: 0,
+    };
+
+    /// Decodes the blob for the operation described by `entry`, reusing the
+    /// most-recently-decoded blob when consecutive reads land on the same
+    /// operation.
+    fn decode_cached(
+        &self,
+        entry: &index::IndexEntry,
+        expected_size: u64,
+    ) -> std::io::Result<Vec<u8>> {
+        // Keyed on the destination block range rather than (data_offset,
+        // data_length): `Type::Zero` operations all share data_offset == 0
+        // && data_length == 0, so two differently-sized zero-fill operations
+        // back to back would otherwise hit this cache and hand back a
+        // wrong-length blob for the second one.
+        let key = (entry.start_block, entry.end_block);
+
+        let mut cache = self.cache.lock().unwrap();
+        if let Some(cached) = cache.as_ref() {
+            if cached.key == key {
+                return Ok(cached.blob.clone());
+            }
+        }
+
+        let raw = self
+            .payload
+            .read_data_blob(entry.data_offset, entry.data_length)?;
+        let decoded = decode_blob(entry.operation_type, raw, expected_size)?;
+
+        *cache = Some(DecodedBlobCache {
+            key,
+            blob: decoded.clone(),
+        });
+        Ok(decoded)
+    }
+
+    /// Reads `len` bytes at `offset` from a single partition image, splicing
+    /// together the operations that overlap the requested range.
+    fn read_partition(&self, file: &PartitionFile, offset: u64, len: u64) -> std::io::Result<Vec<u8>> {
+        let end = (offset + len).min(file.size);
+        if offset >= end {
+            return Ok(Vec::new());
+        }
+
+        let start_block = offset / PartitionExtent::BLOCK_SIZE;
+        let end_block = end.div_ceil(PartitionExtent::BLOCK_SIZE);
+
+        let mut out = Vec::with_capacity((end - offset) as usize);
+        for entry in file.index.overlapping(start_block, end_block) {
+            let entry_start = entry.start_block * PartitionExtent::BLOCK_SIZE;
+            let entry_end = entry.end_block * PartitionExtent::BLOCK_SIZE;
+            let expected_size = (entry.end_block - entry.start_block) * PartitionExtent::BLOCK_SIZE;
+
+            let decoded = self.decode_cached(entry, expected_size)?;
+            if decoded.len() as u64 != expected_size {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!(
+                        "decoded operation for blocks {}..{} is {} bytes, expected {expected_size}",
+                        entry.start_block,
+                        entry.end_block,
+                        decoded.len()
+                    ),
+                ));
+            }
+
+            let slice_start = offset.max(entry_start) - entry_start;
+            let slice_end = end.min(entry_end) - entry_start;
+            out.extend_from_slice(&decoded[slice_start as usize..slice_end as usize]);
+        }
+
+        Ok(out)
+    }
+}
+
+impl Filesystem for PayloadFs {
+    fn lookup(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        if parent != 1 {
+            reply.error(libc::ENOENT);
+            return;
+        }
+
+        let Some(name) = name.to_str() else {
+            reply.error(libc::ENOENT);
+            return;
+        };
+        let Some(stripped) = name.strip_suffix(".img") else {
+            reply.error(libc::ENOENT);
+            return;
+        };
+
+        match self.ino_by_name(stripped) {
+            Some(ino) => {
+                let size = self.partition_by_ino(ino).unwrap().size;
+                reply.entry(&TTL, &Self::file_attr(ino, size), 0)
+            }
+            None => reply.error(libc::ENOENT),
+        }
+    }
+
+    fn getattr(&mut self, _req: &Request<'_>, ino: u64, _fh: Option<u64>, reply: ReplyAttr) {
+        if ino == 1 {
+            reply.attr(&TTL, &Self::ROOT_ATTR);
+            return;
+        }
+
+        match self.partition_by_ino(ino) {
+            Some(file) => reply.attr(&TTL, &Self::file_attr(ino, file.size)),
+            None => reply.error(libc::ENOENT),
+        }
+    }
+
+    fn read(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        size: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyData,
+    ) {
+        let Some(file) = self.partition_by_ino(ino) else {
+            reply.error(libc::ENOENT);
+            return;
+        };
+
+        match self.read_partition(file, offset as u64, size as u64) {
+            Ok(data) => reply.data(&data),
+            Err(_) => reply.error(libc::EIO),
+        }
+    }
+
+    fn readdir(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        mut reply: ReplyDirectory,
+    ) {
+        if ino != 1 {
+            reply.error(libc::ENOENT);
+            return;
+        }
+
+        let mut entries = vec![
+            (1, FileType::Directory, ".".to_owned()),
+            (1, FileType::Directory, "..".to_owned()),
+        ];
+        entries.extend(
+            self.partitions
+                .iter()
+                .enumerate()
+                .map(|(idx, file)| (idx as u64 + 2, FileType::RegularFile, format!("{}.img", file.name))),
+        );
+
+        for (i, (ino, kind, name)) in entries.into_iter().enumerate().skip(offset as usize) {
+            if reply.add(ino, (i + 1) as i64, kind, name) {
+                break;
+            }
+        }
+        reply.ok();
+    }
+}
+
+/// Mounts `payload` at `mountpoint`, blocking until the filesystem is
+/// unmounted (e.g. via `umount`/ctrl-c).
+pub fn mount(payload: Payload, mountpoint: &Path) -> std::io::Result<()> {
+    let options = vec![
+        MountOption::RO,
+        MountOption::FSName("payload-dumper".to_owned()),
+    ];
+    fuser::mount2(PayloadFs::new(payload), mountpoint, &options)
+}
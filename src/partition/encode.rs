@@ -0,0 +1,125 @@
+use std::io::{Read, Result, Write};
+
+use sha2::{Digest, Sha256};
+use xz::write::XzEncoder;
+use zstd::Encoder as ZstdEncoder;
+
+use super::PartitionExtent;
+use crate::payload::update_metadata::install_operation::Type;
+
+/// Operations are split into chunks this many blocks wide (16 MiB) so a
+/// single compressed blob never grows unreasonably large, mirroring how
+/// real OTA payloads are chunked.
+const CHUNK_BLOCKS: u64 = 4096;
+
+/// One operation's worth of encoded output: a compressed (or raw) blob plus
+/// the destination block range and data hash it belongs at, ready to be
+/// placed into a `DeltaArchiveManifest`.
+pub struct EncodedOperation {
+    pub operation_type: Type,
+    pub data: Vec<u8>,
+    pub data_sha256_hash: [u8; 32],
+    pub start_block: u64,
+    pub num_blocks: u64,
+}
+
+/// Chunks a partition image into block-aligned extents and compresses each
+/// one, the inverse of [`super::PartitionDecoder`].
+pub struct PartitionEncoder {
+    operation_type: Type,
+    zstd_level: i32,
+}
+
+impl PartitionEncoder {
+    pub fn new(operation_type: Type) -> Self {
+        Self {
+            operation_type,
+            zstd_level: 19,
+        }
+    }
+
+    pub fn with_zstd_level(mut self, level: i32) -> Self {
+        self.zstd_level = level;
+        self
+    }
+
+    /// Reads `image` to completion, returning its encoded operations in
+    /// ascending block order plus the SHA256 hash of the whole image
+    /// zero-padded to a whole number of blocks, for `new_partition_info`.
+    pub fn encode(&self, mut image: impl Read) -> Result<(Vec<EncodedOperation>, [u8; 32])> {
+        let chunk_size = (CHUNK_BLOCKS * PartitionExtent::BLOCK_SIZE) as usize;
+        let mut operations = Vec::new();
+        let mut image_hasher = Sha256::new();
+        let mut start_block = 0u64;
+
+        loop {
+            let mut chunk = vec![0u8; chunk_size];
+            let read = read_fully(&mut image, &mut chunk)?;
+            if read == 0 {
+                break;
+            }
+
+            let padded_len = (read as u64).div_ceil(PartitionExtent::BLOCK_SIZE) * PartitionExtent::BLOCK_SIZE;
+            chunk.truncate(padded_len as usize);
+            chunk[read..].fill(0);
+
+            // Hash the zero-padded chunk, not just the bytes actually read:
+            // `Payload::extract`'s whole-image verification hashes every
+            // reconstructed block up to `size.div_ceil(BLOCK_SIZE)`, padding
+            // included, so `new_partition_info.hash` must match that exactly.
+            image_hasher.update(&chunk);
+
+            let data = self.compress(&chunk)?;
+            let data_sha256_hash = Sha256::digest(&data).into();
+            let num_blocks = padded_len / PartitionExtent::BLOCK_SIZE;
+
+            operations.push(EncodedOperation {
+                operation_type: self.operation_type,
+                data,
+                data_sha256_hash,
+                start_block,
+                num_blocks,
+            });
+            start_block += num_blocks;
+
+            if read < chunk_size {
+                break;
+            }
+        }
+
+        Ok((operations, image_hasher.finalize().into()))
+    }
+
+    fn compress(&self, chunk: &[u8]) -> Result<Vec<u8>> {
+        match self.operation_type {
+            Type::Replace => Ok(chunk.to_vec()),
+            Type::ReplaceXz => {
+                let mut encoder = XzEncoder::new(Vec::new(), 6);
+                encoder.write_all(chunk)?;
+                encoder.finish()
+            }
+            Type::ReplaceZstd => {
+                let mut encoder = ZstdEncoder::new(Vec::new(), self.zstd_level)?;
+                encoder.write_all(chunk)?;
+                encoder.finish()
+            }
+            _ => Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                format!("Unsupported encode operation type: {:?}", self.operation_type),
+            )),
+        }
+    }
+}
+
+/// Fills `buf` completely unless the reader hits EOF first, returning how
+/// many bytes were actually read.
+fn read_fully(reader: &mut impl Read, buf: &mut [u8]) -> Result<usize> {
+    let mut total = 0;
+    while total < buf.len() {
+        match reader.read(&mut buf[total..])? {
+            0 => break,
+            n => total += n,
+        }
+    }
+    Ok(total)
+}
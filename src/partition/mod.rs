@@ -1,7 +1,10 @@
+mod encode;
 mod extent;
 mod read;
 mod write;
 
+pub use encode::{EncodedOperation, PartitionEncoder};
 pub use extent::PartitionExtent;
 pub use read::PartitionReader;
+pub(crate) use write::decode_blob;
 pub use write::PartitionDecoder;
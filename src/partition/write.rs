@@ -23,29 +23,7 @@ impl<W: Write + Seek> PartitionDecoder<W> {
         let operation_type = extent.operation_type();
         let start_offset = extent.start_block() * PartitionExtent::BLOCK_SIZE;
         let expected_size = extent.num_blocks() * PartitionExtent::BLOCK_SIZE;
-        let blob = extent.into_raw();
-
-        let decoded = match operation_type {
-            Type::Zero => vec![0u8; expected_size as usize],
-            Type::Replace => blob,
-            Type::ReplaceXz | Type::ReplaceBz | Type::ReplaceZstd => {
-                let mut buf = vec![0u8; expected_size as usize];
-                let mut decoder: Box<dyn Read> = match operation_type {
-                    Type::ReplaceXz => Box::new(XzDecoder::new(blob.as_slice())),
-                    Type::ReplaceZstd => Box::new(ZstdDecoder::new(blob.as_slice()).unwrap()),
-                    _ => Box::new(BzDecoder::new(blob.as_slice())),
-                };
-
-                decoder.read_exact(&mut buf)?;
-                buf
-            }
-            _ => {
-                return Err(std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    format!("Unsupported operation type: {:?}", operation_type),
-                ))
-            }
-        };
+        let decoded = decode_blob(operation_type, extent.into_raw(), expected_size)?;
 
         self.destination
             .seek(std::io::SeekFrom::Start(start_offset))
@@ -55,3 +33,29 @@ impl<W: Write + Seek> PartitionDecoder<W> {
         Ok(())
     }
 }
+
+/// Decodes a raw operation blob into its plain block data, dispatching on the
+/// operation's compression type. Shared by [`PartitionDecoder::write_extent`]
+/// and anything else (e.g. the FUSE mount) that needs to materialize an
+/// operation's bytes without owning a `Write + Seek` destination.
+pub(crate) fn decode_blob(operation_type: Type, blob: Vec<u8>, expected_size: u64) -> Result<Vec<u8>> {
+    match operation_type {
+        Type::Zero => Ok(vec![0u8; expected_size as usize]),
+        Type::Replace => Ok(blob),
+        Type::ReplaceXz | Type::ReplaceBz | Type::ReplaceZstd => {
+            let mut buf = vec![0u8; expected_size as usize];
+            let mut decoder: Box<dyn Read> = match operation_type {
+                Type::ReplaceXz => Box::new(XzDecoder::new(blob.as_slice())),
+                Type::ReplaceZstd => Box::new(ZstdDecoder::new(blob.as_slice()).unwrap()),
+                _ => Box::new(BzDecoder::new(blob.as_slice())),
+            };
+
+            decoder.read_exact(&mut buf)?;
+            Ok(buf)
+        }
+        _ => Err(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            format!("Unsupported operation type: {:?}", operation_type),
+        )),
+    }
+}
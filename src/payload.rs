@@ -1,4 +1,4 @@
-mod update_metadata {
+pub(crate) mod update_metadata {
     include!(concat!(env!("OUT_DIR"), "/chromeos_update_engine.rs"));
 }
 use bzip2::bufread::BzDecoder;
@@ -6,14 +6,18 @@ use indicatif::{HumanBytes, MultiProgress, ProgressBar, ProgressStyle};
 use prost::Message;
 use sha2::{Digest, Sha256};
 use std::{fmt::Display, fs::File, io::Read, os::unix::fs::FileExt, path::Path};
-use update_metadata::{install_operation::Type, DeltaArchiveManifest, PartitionUpdate, Signatures};
+use update_metadata::{install_operation::Type, DeltaArchiveManifest, Extent, PartitionUpdate, Signatures};
 use xz::bufread::XzDecoder;
 use zstd::Decoder;
 
-const PAYLOAD_HEADER_MAGIC: &str = "CrAU";
+use crate::delta::{apply_bspatch, apply_puffpatch};
+use crate::source::{is_url, HttpReader, ReadAt, SplitFile};
+
+pub(crate) const PAYLOAD_HEADER_MAGIC: &str = "CrAU";
 /// From: https://android.googlesource.com/platform/system/update_engine/+/refs/heads/main/update_engine.conf
-const PAYLOAD_MAJOR_VERSION: u64 = 2;
-const HEADER_SIZE: u64 = size_of::<Header>() as u64;
+pub(crate) const PAYLOAD_MAJOR_VERSION: u64 = 2;
+/// Magic (4) + major_version (8) + manifest_size (8) + manifest_signature_size (4).
+const HEADER_SIZE: u64 = 24;
 const BLOCK_SIZE: u64 = 4096;
 
 #[derive(Debug)]
@@ -46,47 +50,38 @@ pub struct Payload {
     manifest: Box<DeltaArchiveManifest>,
     /// The signature of the first five fields. There could be multiple signatures if the key has changed.
     manifest_signature: Box<Signatures>,
-    file: Box<File>,
+    source: Box<dyn ReadAt>,
 
     multi_progress: MultiProgress,
     quiet: bool,
     verify: bool,
 }
 
-impl TryFrom<&mut File> for Header {
-    type Error = std::io::Error;
-
-    fn try_from(file: &mut File) -> Result<Self, Self::Error> {
-        // Read and validate version
-        let major_version = {
-            let mut buf = [0u8; 8];
-            file.read_exact(&mut buf)?;
-            let version = u64::from_be_bytes(buf);
-
-            if version != PAYLOAD_MAJOR_VERSION {
-                return Err(std::io::Error::new(
-                    std::io::ErrorKind::InvalidData,
-                    format!("Invalid payload version: {version}"),
-                ));
-            }
-            version
-        };
+impl Header {
+    /// Parses a header out of its fixed-size on-disk encoding, as read from
+    /// offset `0` of the payload by [`Payload::try_from`].
+    fn parse(buf: &[u8; HEADER_SIZE as usize]) -> Result<Self, std::io::Error> {
+        let magic_number: [u8; 4] = buf[0..4].try_into().unwrap();
+        if magic_number != PAYLOAD_HEADER_MAGIC.as_bytes() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Invalid android payload magic number",
+            ));
+        }
 
-        // Read manifest and signature sizes
-        let manifest_size = {
-            let mut buf = [0u8; 8];
-            file.read_exact(&mut buf)?;
-            u64::from_be_bytes(buf)
-        };
+        let major_version = u64::from_be_bytes(buf[4..12].try_into().unwrap());
+        if major_version != PAYLOAD_MAJOR_VERSION {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Invalid payload version: {major_version}"),
+            ));
+        }
 
-        let manifest_signature_size = {
-            let mut buf = [0u8; 4];
-            file.read_exact(&mut buf)?;
-            u32::from_be_bytes(buf)
-        };
+        let manifest_size = u64::from_be_bytes(buf[12..20].try_into().unwrap());
+        let manifest_signature_size = u32::from_be_bytes(buf[20..24].try_into().unwrap());
 
         Ok(Header {
-            magic_number: PAYLOAD_HEADER_MAGIC.as_bytes().try_into().unwrap(),
+            magic_number,
             major_version,
             manifest_size,
             manifest_signature_size,
@@ -94,31 +89,33 @@ impl TryFrom<&mut File> for Header {
     }
 }
 
-impl TryFrom<&Path> for Payload {
+impl TryFrom<&str> for Payload {
     type Error = std::io::Error;
-    fn try_from(path: &Path) -> Result<Self, Self::Error> {
-        let mut file = File::open(path)?;
-
-        // Validate magic number
-        let magic = {
-            let mut buffer = [0u8; 4];
-            file.read_exact(&mut buffer)?;
-            String::from_utf8_lossy(&buffer).to_string()
-        };
 
-        if magic != PAYLOAD_HEADER_MAGIC {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidInput,
-                "Invalid android payload magic number",
-            ));
-        }
+    /// Opens a payload from a local path or, if `path_or_url` looks like an
+    /// `http(s)://` URL, from a remote host. Either way only the header,
+    /// manifest and signature are fetched up front; operation blobs are
+    /// pulled lazily by [`Payload::extract`].
+    fn try_from(path_or_url: &str) -> Result<Self, Self::Error> {
+        let source: Box<dyn ReadAt> = if is_url(path_or_url) {
+            Box::new(HttpReader::new(path_or_url)?)
+        } else {
+            let path = Path::new(path_or_url);
+            match SplitFile::try_discover(path)? {
+                Some(split) => Box::new(split),
+                None => Box::new(File::open(path)?),
+            }
+        };
 
-        // Read header, manifest, and signature
-        let header = Header::try_from(&mut file)?;
+        let header = {
+            let mut buf = [0u8; HEADER_SIZE as usize];
+            source.read_exact_at(&mut buf, 0)?;
+            Header::parse(&buf)?
+        };
 
         let manifest = {
             let mut buf = vec![0u8; header.manifest_size as usize];
-            file.read_exact(&mut buf)?;
+            source.read_exact_at(&mut buf, HEADER_SIZE)?;
             let mut manifest = DeltaArchiveManifest::decode(&buf[..])?;
 
             // Sort partitions by name for later binary search
@@ -130,7 +127,7 @@ impl TryFrom<&Path> for Payload {
 
         let manifest_signature = {
             let mut buf = vec![0u8; header.manifest_signature_size as usize];
-            file.read_exact(&mut buf)?;
+            source.read_exact_at(&mut buf, HEADER_SIZE + header.manifest_size)?;
             Box::new(Signatures::decode(&buf[..])?)
         };
 
@@ -138,7 +135,7 @@ impl TryFrom<&Path> for Payload {
             header,
             manifest,
             manifest_signature,
-            file: Box::new(file),
+            source,
             multi_progress: MultiProgress::new(),
             quiet: false,
             verify: true,
@@ -159,7 +156,7 @@ impl Payload {
         self
     }
 
-    fn data_offset(&self) -> u64 {
+    pub(crate) fn data_offset(&self) -> u64 {
         HEADER_SIZE + self.header.manifest_size + self.header.manifest_signature_size as u64
     }
 
@@ -167,10 +164,9 @@ impl Payload {
         &self.header
     }
 
-    fn read_data_blob(&self, offset: u64, len: u64) -> Result<Vec<u8>, std::io::Error> {
+    pub(crate) fn read_data_blob(&self, offset: u64, len: u64) -> Result<Vec<u8>, std::io::Error> {
         let mut buf = vec![0u8; len as usize];
-        self.file
-            .read_exact_at(&mut buf, self.data_offset() + offset)?;
+        self.source.read_exact_at(&mut buf, self.data_offset() + offset)?;
         Ok(buf)
     }
 
@@ -197,13 +193,18 @@ impl Payload {
         self.manifest.partitions.as_slice()
     }
 
-    fn partition(&self, partition: &str) -> Result<&PartitionUpdate, usize> {
+    pub(crate) fn partition(&self, partition: &str) -> Result<&PartitionUpdate, usize> {
         self.partitions()
             .binary_search_by_key(&partition, |p| p.partition_name.as_str())
             .map(|idx| &self.partitions()[idx])
     }
 
-    pub fn extract(&self, partition: &str, output_dir: &Path) -> Result<(), std::io::Error> {
+    pub fn extract(
+        &self,
+        partition: &str,
+        output_dir: &Path,
+        source_dir: Option<&Path>,
+    ) -> Result<(), std::io::Error> {
         let partition = if let Ok(partition) = self.partition(partition) {
             partition
         } else {
@@ -212,8 +213,9 @@ impl Payload {
         };
         let name = partition.partition_name.as_str();
         let file = File::create(output_dir.join(format!("{}.img", name)))?;
+        let partition_info = partition.new_partition_info.as_ref().unwrap();
         let progress_bar = self.multi_progress.add(
-            ProgressBar::new(partition.new_partition_info.as_ref().unwrap().size() as u64)
+            ProgressBar::new(partition_info.size() as u64)
                 .with_message(name.to_owned())
                 .with_style(
                     ProgressStyle::with_template(
@@ -224,6 +226,12 @@ impl Payload {
                 ),
         );
 
+        // Whole-image hash, accumulated incrementally from decoded extents in
+        // ascending block order (zero-filling any gaps) so we don't have to
+        // hold the reconstructed image in memory to verify it.
+        let mut whole_image_hasher = self.verify.then(Sha256::new);
+        let mut next_block = 0u64;
+
         for operation in partition.operations.iter() {
             let dst_extent = operation.dst_extents.first().ok_or_else(|| {
                 std::io::Error::new(
@@ -233,47 +241,145 @@ impl Payload {
             })?;
 
             let expected_size = dst_extent.num_blocks() * BLOCK_SIZE;
-            let blob = self.read_data_blob(operation.data_offset(), operation.data_length())?;
-
-            // Verify hash for non-zero operations
-            if self.verify && operation.r#type() != Type::Zero {
-                let hash = hex::encode(Sha256::digest(&blob));
-                let expected_hash = hex::encode(operation.data_sha256_hash());
-
-                if hash != expected_hash {
-                    return Err(std::io::Error::new(
-                        std::io::ErrorKind::InvalidData,
-                        format!("SHA256 hash mismatch. Expected: {expected_hash}, Got: {hash}"),
-                    ));
+            let operation_type = operation.r#type();
+
+            // SOURCE_COPY carries no data blob of its own: the destination is
+            // assembled purely from blocks of the old partition image.
+            let decoded = if operation_type == Type::SourceCopy {
+                let source_dir = source_dir.ok_or_else(|| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        format!(
+                            "partition {name} has a SOURCE_COPY operation but no --source-dir was given"
+                        ),
+                    )
+                })?;
+                read_source_extents(source_dir, name, &operation.src_extents)?
+            } else {
+                let blob = self.read_data_blob(operation.data_offset(), operation.data_length())?;
+
+                // Verify hash for non-zero operations
+                if self.verify && operation_type != Type::Zero {
+                    let hash = hex::encode(Sha256::digest(&blob));
+                    let expected_hash = hex::encode(operation.data_sha256_hash());
+
+                    if hash != expected_hash {
+                        return Err(std::io::Error::new(
+                            std::io::ErrorKind::InvalidData,
+                            format!("SHA256 hash mismatch. Expected: {expected_hash}, Got: {hash}"),
+                        ));
+                    }
                 }
-            }
 
-            let decoded = match operation.r#type() {
-                Type::Zero => vec![0u8; expected_size as usize],
-                Type::Replace => blob,
-                Type::ReplaceXz | Type::ReplaceBz | Type::ReplaceZstd => {
-                    let mut decoder: Box<dyn Read> = match operation.r#type() {
-                        Type::ReplaceXz => Box::new(XzDecoder::new(blob.as_slice())),
-                        Type::ReplaceZstd => Box::new(Decoder::new(blob.as_slice())?),
-                        _ => Box::new(BzDecoder::new(blob.as_slice())),
-                    };
-                    let mut decoded = vec![0u8; expected_size as usize];
-                    decoder.read_exact(&mut decoded)?;
-                    decoded
-                }
-                _ => {
-                    return Err(std::io::Error::new(
-                        std::io::ErrorKind::Other,
-                        format!("Invalid operation type: {:?}", operation.r#type()),
-                    ))
+                match operation_type {
+                    Type::Zero => vec![0u8; expected_size as usize],
+                    Type::Replace => blob,
+                    Type::ReplaceXz | Type::ReplaceBz | Type::ReplaceZstd => {
+                        let mut decoder: Box<dyn Read> = match operation_type {
+                            Type::ReplaceXz => Box::new(XzDecoder::new(blob.as_slice())),
+                            Type::ReplaceZstd => Box::new(Decoder::new(blob.as_slice())?),
+                            _ => Box::new(BzDecoder::new(blob.as_slice())),
+                        };
+                        let mut decoded = vec![0u8; expected_size as usize];
+                        decoder.read_exact(&mut decoded)?;
+                        decoded
+                    }
+                    Type::SourceBsdiff | Type::BrotliBsdiff | Type::Puffdiff => {
+                        let source_dir = source_dir.ok_or_else(|| {
+                            std::io::Error::new(
+                                std::io::ErrorKind::InvalidInput,
+                                format!(
+                                    "partition {name} has a {operation_type:?} operation but no --source-dir was given"
+                                ),
+                            )
+                        })?;
+                        let old = read_source_extents(source_dir, name, &operation.src_extents)?;
+
+                        if operation_type == Type::Puffdiff {
+                            apply_puffpatch(&old, &blob, expected_size as usize)?
+                        } else {
+                            let patch = if operation_type == Type::BrotliBsdiff {
+                                let mut decompressed = Vec::new();
+                                brotli::Decompressor::new(blob.as_slice(), 4096)
+                                    .read_to_end(&mut decompressed)?;
+                                decompressed
+                            } else {
+                                blob
+                            };
+                            apply_bspatch(&old, &patch, expected_size as usize)?
+                        }
+                    }
+                    _ => {
+                        return Err(std::io::Error::new(
+                            std::io::ErrorKind::Other,
+                            format!("Invalid operation type: {operation_type:?}"),
+                        ))
+                    }
                 }
             };
 
+            if let Some(hasher) = whole_image_hasher.as_mut() {
+                if dst_extent.start_block() > next_block {
+                    feed_zero_fill(hasher, (dst_extent.start_block() - next_block) * BLOCK_SIZE);
+                }
+                hasher.update(&decoded);
+                next_block = dst_extent.start_block() + dst_extent.num_blocks();
+            }
+
             file.write_all_at(&decoded, dst_extent.start_block() * BLOCK_SIZE)?;
 
             progress_bar.inc(decoded.len() as u64);
         }
 
+        if let Some(mut hasher) = whole_image_hasher {
+            let total_blocks = partition_info.size().div_ceil(BLOCK_SIZE);
+            if total_blocks > next_block {
+                feed_zero_fill(&mut hasher, (total_blocks - next_block) * BLOCK_SIZE);
+            }
+
+            let hash = hasher.finalize();
+            let expected_hash = partition_info.hash();
+            if hash.as_slice() != expected_hash {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!(
+                        "Partition {name} failed whole-image verification. Expected: {}, Got: {}",
+                        hex::encode(expected_hash),
+                        hex::encode(hash)
+                    ),
+                ));
+            }
+        }
+
         Ok(())
     }
 }
+
+/// Gathers the bytes covered by `extents` out of `<source_dir>/<name>.img`,
+/// the old partition image a delta operation patches against.
+fn read_source_extents(
+    source_dir: &Path,
+    name: &str,
+    extents: &[Extent],
+) -> Result<Vec<u8>, std::io::Error> {
+    let source_file = File::open(source_dir.join(format!("{name}.img")))?;
+
+    let mut buf = Vec::new();
+    for extent in extents {
+        let mut chunk = vec![0u8; (extent.num_blocks() * BLOCK_SIZE) as usize];
+        source_file.read_exact_at(&mut chunk, extent.start_block() * BLOCK_SIZE)?;
+        buf.extend_from_slice(&chunk);
+    }
+    Ok(buf)
+}
+
+/// Feeds `len` zero bytes into `hasher` without materializing a `len`-sized
+/// buffer, for the unbacked gaps between operations.
+fn feed_zero_fill(hasher: &mut Sha256, mut len: u64) {
+    const ZERO_CHUNK: [u8; 4096] = [0u8; 4096];
+    while len > 0 {
+        let take = len.min(ZERO_CHUNK.len() as u64) as usize;
+        hasher.update(&ZERO_CHUNK[..take]);
+        len -= take as u64;
+    }
+}
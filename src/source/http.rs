@@ -0,0 +1,65 @@
+use reqwest::blocking::Client;
+use reqwest::header::RANGE;
+use reqwest::StatusCode;
+
+use super::ReadAt;
+
+/// Reads a remote payload on demand via HTTP `Range` requests, so extracting
+/// a single partition only pulls the bytes that partition actually needs
+/// instead of the whole (often multi-gigabyte) OTA file.
+pub struct HttpReader {
+    client: Client,
+    url: String,
+}
+
+impl HttpReader {
+    pub fn new(url: impl Into<String>) -> std::io::Result<Self> {
+        Ok(Self {
+            client: Client::new(),
+            url: url.into(),
+        })
+    }
+}
+
+impl ReadAt for HttpReader {
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<()> {
+        if buf.is_empty() {
+            return Ok(());
+        }
+
+        let end = offset + buf.len() as u64 - 1;
+        let response = self
+            .client
+            .get(&self.url)
+            .header(RANGE, format!("bytes={offset}-{end}"))
+            .send()
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))?;
+
+        // A plain 200 OK means the server ignored our Range header and sent
+        // the whole file; accepting it here would silently hand back the
+        // first N bytes of the file instead of the bytes at `offset`.
+        if response.status() != StatusCode::PARTIAL_CONTENT {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                format!(
+                    "HTTP request for bytes={offset}-{end} failed: server does not support range requests ({})",
+                    response.status()
+                ),
+            ));
+        }
+
+        let body = response
+            .bytes()
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))?;
+
+        if (body.len() as u64) < buf.len() as u64 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                format!("expected {} bytes, server returned {}", buf.len(), body.len()),
+            ));
+        }
+
+        buf.copy_from_slice(&body[..buf.len()]);
+        Ok(())
+    }
+}
@@ -0,0 +1,29 @@
+//! Abstraction over where a payload's bytes come from, so `Payload` isn't
+//! tied to a local `File`.
+
+mod http;
+mod split;
+
+use std::fs::File;
+
+pub use http::HttpReader;
+pub use split::SplitFile;
+
+/// A source that can be read from at an arbitrary absolute offset without
+/// disturbing any other concurrent read, the way `FileExt::read_exact_at`
+/// behaves for local files.
+pub trait ReadAt: Send + Sync {
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<()>;
+}
+
+impl ReadAt for File {
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<()> {
+        std::os::unix::fs::FileExt::read_exact_at(self, buf, offset)
+    }
+}
+
+/// Whether `path` names a remote payload to fetch over HTTP rather than a
+/// local file.
+pub fn is_url(path: &str) -> bool {
+    path.starts_with("http://") || path.starts_with("https://")
+}
@@ -0,0 +1,116 @@
+use std::fs::File;
+use std::io;
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+
+use super::ReadAt;
+
+/// Presents a payload split across sequentially numbered part files (e.g.
+/// `payload.bin.000`, `payload.bin.001`, ...) as one contiguous source, the
+/// way `io/split.rs` stitches split-disc parts together in nod-rs.
+pub struct SplitFile {
+    parts: Vec<File>,
+    /// Absolute offset each part starts at, parallel to `parts`.
+    part_offsets: Vec<u64>,
+    part_lens: Vec<u64>,
+}
+
+impl SplitFile {
+    /// If `first_part` names a numbered split part (a purely-numeric
+    /// extension), discovers and opens its siblings and returns the combined
+    /// source. Returns `Ok(None)` for an ordinary, non-split path.
+    pub fn try_discover(first_part: &Path) -> io::Result<Option<Self>> {
+        if !is_split_part(first_part) {
+            return Ok(None);
+        }
+
+        let dir = first_part
+            .parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .unwrap_or_else(|| Path::new("."));
+        let stem = first_part.file_stem().unwrap();
+        let ext_width = first_part.extension().unwrap().len();
+
+        let mut part_paths: Vec<PathBuf> = std::fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.file_stem() == Some(stem)
+                    && path
+                        .extension()
+                        .and_then(|ext| ext.to_str())
+                        .is_some_and(|ext| ext.len() == ext_width && ext.bytes().all(|b| b.is_ascii_digit()))
+            })
+            .collect();
+
+        part_paths.sort_by_key(|path| {
+            path.extension()
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .parse::<u64>()
+                .unwrap()
+        });
+
+        let mut parts = Vec::with_capacity(part_paths.len());
+        let mut part_offsets = Vec::with_capacity(part_paths.len());
+        let mut part_lens = Vec::with_capacity(part_paths.len());
+        let mut cumulative = 0u64;
+
+        for path in &part_paths {
+            let file = File::open(path)?;
+            let len = file.metadata()?.len();
+
+            part_offsets.push(cumulative);
+            part_lens.push(len);
+            cumulative += len;
+            parts.push(file);
+        }
+
+        Ok(Some(Self {
+            parts,
+            part_offsets,
+            part_lens,
+        }))
+    }
+}
+
+impl ReadAt for SplitFile {
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        let mut offset = offset;
+        let mut remaining = buf;
+
+        while !remaining.is_empty() {
+            let part_idx = self
+                .part_offsets
+                .partition_point(|&start| start <= offset)
+                .checked_sub(1)
+                .ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::UnexpectedEof, "read before start of split file")
+                })?;
+
+            let part_offset = offset - self.part_offsets[part_idx];
+            if part_offset >= self.part_lens[part_idx] {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "read past end of split file",
+                ));
+            }
+            let available = self.part_lens[part_idx] - part_offset;
+
+            let chunk_len = remaining.len().min(available as usize);
+            self.parts[part_idx].read_exact_at(&mut remaining[..chunk_len], part_offset)?;
+
+            offset += chunk_len as u64;
+            remaining = &mut remaining[chunk_len..];
+        }
+
+        Ok(())
+    }
+}
+
+fn is_split_part(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| !ext.is_empty() && ext.bytes().all(|b| b.is_ascii_digit()))
+}